@@ -9,4 +9,8 @@ pub struct Config {
 pub struct ServerConfig {
     pub file_path: String,
     pub binding_address: String,
+    /// Optional MRT/RIB routing-table dump to load alongside the CSV, in the
+    /// `bgpdump -m` text format (see `mrt::read_mrt_ribs_from_file`).
+    #[serde(default)]
+    pub mrt_file_path: Option<String>,
 }
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use axum::http::{ header, StatusCode };
+use axum::response::{ IntoResponse, Response };
+
+use crate::hashmap::IPRange;
+
+/// Output format for a blocklist export, selected via `?format=`.
+pub enum ExportFormat {
+    Json,
+    Nftables,
+    Ipset,
+}
+
+impl ExportFormat {
+    pub fn from_query(format: Option<&str>) -> Self {
+        match format {
+            Some("nftables") => ExportFormat::Nftables,
+            Some("ipset") => ExportFormat::Ipset,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+/// Renders `ranges` as a blocklist in the requested format and wraps it in
+/// an HTTP response with the appropriate content type. `set_name` is used
+/// as the nftables set / ipset name and is sanitized to `[a-zA-Z0-9_]`.
+pub fn render(ranges: &[Arc<IPRange>], format: ExportFormat, set_name: &str) -> Response {
+    let set_name = sanitize_set_name(set_name);
+    match format {
+        ExportFormat::Json => {
+            let ranges: Vec<&IPRange> = ranges
+                .iter()
+                .map(|range| range.as_ref())
+                .collect();
+            axum::Json(ranges).into_response()
+        }
+        ExportFormat::Nftables =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                render_nftables(ranges, &set_name),
+            ).into_response(),
+        ExportFormat::Ipset =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                render_ipset(ranges, &set_name),
+            ).into_response(),
+    }
+}
+
+fn sanitize_set_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if sanitized.is_empty() { "blocklist".to_string() } else { sanitized }
+}
+
+fn split_by_family(ranges: &[Arc<IPRange>]) -> (Vec<&str>, Vec<&str>) {
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for range in ranges {
+        if range.cidr_range.contains(':') {
+            v6.push(range.cidr_range.as_str());
+        } else {
+            v4.push(range.cidr_range.as_str());
+        }
+    }
+    (v4, v6)
+}
+
+/// Renders an nftables named-set definition, one set per address family.
+fn render_nftables(ranges: &[Arc<IPRange>], set_name: &str) -> String {
+    let (v4, v6) = split_by_family(ranges);
+    let mut out = String::new();
+
+    if !v4.is_empty() {
+        out.push_str(
+            &format!(
+                "table inet filter {{\n  set {set_name}_v4 {{\n    type ipv4_addr\n    flags interval\n    elements = {{ {} }}\n  }}\n}}\n",
+                v4.join(", ")
+            )
+        );
+    }
+    if !v6.is_empty() {
+        out.push_str(
+            &format!(
+                "table inet filter {{\n  set {set_name}_v6 {{\n    type ipv6_addr\n    flags interval\n    elements = {{ {} }}\n  }}\n}}\n",
+                v6.join(", ")
+            )
+        );
+    }
+
+    out
+}
+
+/// Renders an `ipset restore`-compatible file, one set per address family.
+fn render_ipset(ranges: &[Arc<IPRange>], set_name: &str) -> String {
+    let (v4, v6) = split_by_family(ranges);
+    let mut out = String::new();
+
+    if !v4.is_empty() {
+        out.push_str(&format!("create {set_name}_v4 hash:net family inet -exist\n"));
+        for cidr in &v4 {
+            out.push_str(&format!("add {set_name}_v4 {cidr}\n"));
+        }
+    }
+    if !v6.is_empty() {
+        out.push_str(&format!("create {set_name}_v6 hash:net family inet6 -exist\n"));
+        for cidr in &v6 {
+            out.push_str(&format!("add {set_name}_v6 {cidr}\n"));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(cidr: &str, isp: &str, asn: &str) -> Arc<IPRange> {
+        Arc::new(IPRange {
+            cidr_range: Arc::new(cidr.to_string()),
+            isp: Arc::new(isp.to_string()),
+            asn: Arc::new(asn.to_string()),
+            route: None,
+        })
+    }
+
+    #[test]
+    fn test_sanitize_set_name() {
+        assert_eq!(sanitize_set_name("blocked-hosts.v1"), "blocked_hosts_v1");
+        assert_eq!(sanitize_set_name(""), "blocklist");
+        assert_eq!(sanitize_set_name("!!!"), "___");
+    }
+
+    #[test]
+    fn test_render_nftables_mixed_family() {
+        let ranges = vec![
+            range("198.51.100.0/24", "ISP1", "ASN1"),
+            range("2001:db8::/32", "ISP2", "ASN2")
+        ];
+        let out = render_nftables(&ranges, "blocklist");
+
+        assert_eq!(
+            out,
+            concat!(
+                "table inet filter {\n",
+                "  set blocklist_v4 {\n",
+                "    type ipv4_addr\n",
+                "    flags interval\n",
+                "    elements = { 198.51.100.0/24 }\n",
+                "  }\n",
+                "}\n",
+                "table inet filter {\n",
+                "  set blocklist_v6 {\n",
+                "    type ipv6_addr\n",
+                "    flags interval\n",
+                "    elements = { 2001:db8::/32 }\n",
+                "  }\n",
+                "}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_ipset_mixed_family() {
+        let ranges = vec![
+            range("198.51.100.0/24", "ISP1", "ASN1"),
+            range("2001:db8::/32", "ISP2", "ASN2")
+        ];
+        let out = render_ipset(&ranges, "blocklist");
+
+        assert_eq!(
+            out,
+            concat!(
+                "create blocklist_v4 hash:net family inet -exist\n",
+                "add blocklist_v4 198.51.100.0/24\n",
+                "create blocklist_v6 hash:net family inet6 -exist\n",
+                "add blocklist_v6 2001:db8::/32\n"
+            )
+        );
+    }
+}
@@ -1,13 +1,33 @@
 use std::sync::Arc;
+use std::net::IpAddr;
+use std::collections::HashMap;
 use axum::{ http::StatusCode, response::{ IntoResponse, Response } };
 use serde_with::serde_as;
 use serde::{ Deserialize, Serialize };
 use serde_json::json;
-use crate::utils::parse_cidr; // assume parse_cidr(&str) -> (u32, u8)
+use crate::two_level::TwoLevelTable;
+use crate::utils::{ parse_cidr, CidrNetwork };
+
+///
+/// BGP route attributes for a range loaded from an MRT/RIB dump rather than
+/// the plain `cidr,isp,asn` CSV. ASNs are kept as `u32`, not strings, so a
+/// full table of hundreds of thousands of prefixes stays cheap to hold in
+/// memory.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RouteAttrs {
+    /// Ordered from the nearest hop to the origin.
+    pub as_path: Vec<u32>,
+    pub med: Option<u32>,
+    pub local_pref: Option<u32>,
+}
+
+impl RouteAttrs {
+    /// The last hop in the AS path, i.e. the AS that originated the route.
+    pub fn origin_asn(&self) -> Option<u32> {
+        self.as_path.last().copied()
+    }
+}
 
-//
-// Original IPRange type (unchanged)
-//
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IPRange {
@@ -17,6 +37,8 @@ pub struct IPRange {
     pub isp: Arc<String>,
     #[serde_as(as = "Arc<serde_with::DisplayFromStr>")]
     pub asn: Arc<String>,
+    /// Present when this range came from a BGP routing-table dump.
+    pub route: Option<RouteAttrs>,
 }
 
 impl IntoResponse for IPRange {
@@ -26,13 +48,17 @@ impl IntoResponse for IPRange {
             "cidr_range": *self.cidr_range,
             "isp": *self.isp,
             "asn": *self.asn,
+            "as_path": self.route.as_ref().map(|r| &r.as_path),
+            "origin_asn": self.route.as_ref().and_then(|r| r.origin_asn()),
+            "med": self.route.as_ref().and_then(|r| r.med),
+            "pref": self.route.as_ref().and_then(|r| r.local_pref),
         });
         (StatusCode::OK, axum::Json(json_response)).into_response()
     }
 }
 
 ///
-/// A helper structure that stores a parsed IP range.
+/// A helper structure that stores a parsed IPv4 range.
 ///
 #[derive(Clone)]
 struct IPRangeEntry {
@@ -42,8 +68,14 @@ struct IPRangeEntry {
 }
 
 impl IPRangeEntry {
-    fn new(cidr_range: &str, isp: &str, asn: &str) -> Self {
-        let (network, prefix) = parse_cidr(cidr_range);
+    fn new(
+        network: u32,
+        prefix: u8,
+        cidr_range: &str,
+        isp: &str,
+        asn: &str,
+        route: Option<RouteAttrs>
+    ) -> Self {
         IPRangeEntry {
             network,
             prefix,
@@ -51,163 +83,366 @@ impl IPRangeEntry {
                 cidr_range: Arc::from(String::from(cidr_range)),
                 isp: Arc::from(String::from(isp)),
                 asn: Arc::from(String::from(asn)),
+                route,
             }),
         }
     }
-
-    /// Returns the mask (as a u32) for this entry.
-    fn mask(&self) -> u32 {
-        if self.prefix == 0 { 0 } else { !((1u32).wrapping_shl(32 - (self.prefix as u32)) - 1) }
-    }
 }
 
 ///
-/// A high-performance direct lookup table for IP ranges.
+/// A helper structure that stores a parsed IPv6 range.
 ///
-/// We trade extra memory for an O(1) lookup. Instead of iterating over a trie,
-/// we precompute an array of candidate IP ranges. Each IP address, when shifted
-/// by (32 - INDEX_BITS), is used as an index into this table.
+/// Mirrors `IPRangeEntry`, but the network is the full 128-bit address since
+/// IPv6 prefixes don't fit in a `u32`.
 ///
-/// The table is built from a static set of IP ranges, and the value stored
-/// is the one with the longest matching prefix for that index.
+#[derive(Clone)]
+struct IPRangeEntryV6 {
+    network: u128,
+    prefix: u8,
+    ip_range: Arc<IPRange>,
+}
+
+impl IPRangeEntryV6 {
+    fn new(
+        network: u128,
+        prefix: u8,
+        cidr_range: &str,
+        isp: &str,
+        asn: &str,
+        route: Option<RouteAttrs>
+    ) -> Self {
+        IPRangeEntryV6 {
+            network,
+            prefix,
+            ip_range: Arc::new(IPRange {
+                cidr_range: Arc::from(String::from(cidr_range)),
+                isp: Arc::from(String::from(isp)),
+                asn: Arc::from(String::from(asn)),
+                route,
+            }),
+        }
+    }
+}
+
+/// A direct lookup table for IPv4 ranges, backed by `two_level::TwoLevelTable`.
+#[derive(Clone)]
+struct Ipv4Table {
+    table: TwoLevelTable,
+    /// Collected IP range entries (used during table build).
+    entries: Vec<IPRangeEntry>,
+}
+
+impl Ipv4Table {
+    fn new(index_bits: u32) -> Self {
+        assert!(index_bits <= 32);
+        Ipv4Table {
+            table: TwoLevelTable::new(32, index_bits),
+            entries: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, entry: IPRangeEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Rebuilds the direct lookup table from the collected entries.
+    fn build_table(&mut self) {
+        self.table.clear();
+        for entry in &self.entries {
+            self.table.insert(entry.network as u128, entry.prefix, &entry.ip_range);
+        }
+    }
+
+    /// Looks up an IPv4 address (given as u32) and returns the matching IPRange (if any).
+    fn search(&self, ip_addr: u32) -> Option<Arc<IPRange>> {
+        self.table.search(ip_addr as u128)
+    }
+}
+
 ///
+/// A direct lookup table for IPv6 ranges, built on the same
+/// `TwoLevelTable` as `Ipv4Table` but over the full 128-bit address space.
+#[derive(Clone)]
+struct Ipv6Table {
+    table: TwoLevelTable,
+    entries: Vec<IPRangeEntryV6>,
+}
+
+impl Ipv6Table {
+    fn new(index_bits: u32) -> Self {
+        assert!(index_bits <= 128);
+        Ipv6Table {
+            table: TwoLevelTable::new(128, index_bits),
+            entries: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, entry: IPRangeEntryV6) {
+        self.entries.push(entry);
+    }
+
+    fn build_table(&mut self) {
+        self.table.clear();
+        for entry in &self.entries {
+            self.table.insert(entry.network, entry.prefix, &entry.ip_range);
+        }
+    }
+
+    /// Looks up an IPv6 address (given as u128) and returns the matching IPRange (if any).
+    fn search(&self, ip_addr: u128) -> Option<Arc<IPRange>> {
+        self.table.search(ip_addr)
+    }
+}
+
+/// Number of top bits used to index the IPv6 direct lookup table.
 ///
+/// IPv6 prefixes are usually handed out no finer than a /32 or /48, and a
+/// `2^index_bits` table already costs one `Tbl1Slot` (see `two_level`) slot
+/// per entry, so we cap this well below the 128-bit address width.
+const V6_INDEX_BITS: u32 = 24;
+
+/// Dual-stack direct lookup table: an IPv4 table indexed over `u32` and an
+/// IPv6 table indexed over `u128`.
 #[derive(Clone)]
 pub struct IPRangeDirectLookup {
-    /// A vector of (prefix, Arc<IPRange>) for each table slot.
-    /// If no range applies for a given slot, the entry is None.
-    table: Vec<Option<(u8, Arc<IPRange>)>>,
-    /// Collected IP range entries (used during table build).
-    entries: Vec<IPRangeEntry>,
-    /// Number of bits used for the index (must be <= 32).
-    index_bits: u32,
-    /// Table size is 2^(index_bits)
-    table_size: usize,
+    v4: Ipv4Table,
+    v6: Ipv6Table,
+    /// Reverse index: origin ASN -> every range originated by it.
+    by_asn: HashMap<u32, Vec<Arc<IPRange>>>,
+    /// Reverse index: lowercased ISP name -> every range tagged with it.
+    by_isp: HashMap<String, Vec<Arc<IPRange>>>,
 }
 
 impl IPRangeDirectLookup {
     /// Create a new direct lookup structure.
     ///
-    /// * `index_bits` determines the table size (e.g. 20 yields 2^20 = ~1 million entries).
-    ///   More bits means a more precise lookup table (and more memory usage).
+    /// * `index_bits` determines the IPv4 table size (e.g. 20 yields 2^20 = ~1 million entries).
+    ///   The IPv6 table is capped at `V6_INDEX_BITS` regardless, since a table
+    ///   that large is already impractical well before 128 bits.
     pub fn new(index_bits: u32) -> Self {
         assert!(index_bits <= 32);
-        let table_size = 1 << index_bits;
         IPRangeDirectLookup {
-            table: vec![None; table_size],
-            entries: Vec::new(),
-            index_bits,
-            table_size,
+            v4: Ipv4Table::new(index_bits),
+            v6: Ipv6Table::new(index_bits.min(V6_INDEX_BITS)),
+            by_asn: HashMap::new(),
+            by_isp: HashMap::new(),
         }
     }
 
-    /// Inserts an IP range.
+    /// Inserts an IP range (v4 or v6, detected from the CIDR string).
     ///
     /// Note: The lookup table is not updated immediately. Call `build_table()`
     /// after all ranges have been inserted.
     pub fn insert_range(&mut self, cidr_range: &str, isp: &str, asn: &str) {
-        self.entries.push(IPRangeEntry::new(cidr_range, isp, asn));
+        self.insert_range_with_route(cidr_range, isp, asn, None);
     }
 
-    /// Builds the direct lookup table.
-    ///
-    /// For each inserted IP range, we update every table slot that falls under its range,
-    /// only replacing a slot if the new range has a longer prefix (i.e. is a more specific match).
+    /// Inserts an IP range loaded from a BGP routing-table dump, carrying
+    /// its AS-path/MED/local-pref alongside the usual isp/asn columns.
+    pub fn insert_bgp_route(&mut self, cidr_range: &str, isp: &str, asn: &str, route: RouteAttrs) {
+        self.insert_range_with_route(cidr_range, isp, asn, Some(route));
+    }
+
+    /// Silently skips `cidr_range` if it isn't a parseable CIDR, rather than
+    /// panicking and taking the whole load down over one bad line.
+    fn insert_range_with_route(
+        &mut self,
+        cidr_range: &str,
+        isp: &str,
+        asn: &str,
+        route: Option<RouteAttrs>
+    ) {
+        match parse_cidr(cidr_range) {
+            Some(CidrNetwork::V4(network, prefix)) => {
+                self.v4.insert(IPRangeEntry::new(network, prefix, cidr_range, isp, asn, route));
+            }
+            Some(CidrNetwork::V6(network, prefix)) => {
+                self.v6.insert(IPRangeEntryV6::new(network, prefix, cidr_range, isp, asn, route));
+            }
+            None => {}
+        }
+    }
+
+    /// Builds the IPv4/IPv6 direct lookup tables and the ASN/ISP reverse index.
     pub fn build_table(&mut self) {
-        // Clear the table.
-        self.table.fill(None);
+        self.v4.build_table();
+        self.v6.build_table();
+        self.build_reverse_index();
+    }
 
-        // For each IP range entry...
-        for entry in &self.entries {
-            let mask = entry.mask();
-            // Compute the first IP in the range.
-            let start_ip = entry.network & mask;
-            // Compute the number of IP addresses in this range.
-            let count = if entry.prefix == 32 { 1u32 } else { 1u32 << (32 - entry.prefix) };
-
-            // Because our table is indexed by the top `index_bits` of the IP,
-            // determine the indices that this IP range covers.
-            //
-            // For each IP address in the range, the table index is:
-            //    index = ip >> (32 - index_bits)
-            //
-            // Rather than iterate over every IP address in the range,
-            // we compute the range of table indices that may be affected.
-            //
-            // Note: This is an approximation. Some table slots might contain
-            // IP addresses outside the IP range, but we rely on the longest prefix
-            // logic to ensure correctness.
-            let shift = 32 - self.index_bits;
-            let start_index = start_ip >> shift;
-            let end_ip = start_ip.wrapping_add(count - 1);
-            let end_index = end_ip >> shift;
-
-            for index in start_index..=end_index {
-                // Update the table if:
-                // - There is no entry, or
-                // - The current entry's prefix is less specific than this one.
-                if let Some((existing_prefix, _)) = self.table[index as usize] {
-                    if entry.prefix > existing_prefix {
-                        self.table[index as usize] = Some((
-                            entry.prefix,
-                            Arc::clone(&entry.ip_range),
-                        ));
-                    }
-                } else {
-                    self.table[index as usize] = Some((entry.prefix, Arc::clone(&entry.ip_range)));
-                }
+    fn build_reverse_index(&mut self) {
+        self.by_asn.clear();
+        self.by_isp.clear();
+
+        let all_ranges = self.v4.entries
+            .iter()
+            .map(|entry| &entry.ip_range)
+            .chain(self.v6.entries.iter().map(|entry| &entry.ip_range));
+
+        for ip_range in all_ranges {
+            if let Some(asn) = parse_asn(&ip_range.asn) {
+                upsert_by_cidr(self.by_asn.entry(asn).or_default(), ip_range);
+            }
+
+            let isp_key = ip_range.isp.trim().to_lowercase();
+            if !isp_key.is_empty() {
+                upsert_by_cidr(self.by_isp.entry(isp_key).or_default(), ip_range);
             }
         }
     }
 
-    /// Looks up an IP address (given as u32) and returns the matching IPRange (if any).
-    ///
-    /// The lookup is an O(1) array index.
-    pub fn search(&self, ip_addr: u32) -> Option<Arc<IPRange>> {
-        let shift = 32 - self.index_bits;
-        let index = ip_addr >> shift;
-        // Because of the way the table was built, if an entry exists it is the best match.
-        self.table
-            .get(index as usize)
-            .and_then(|entry| entry.as_ref().map(|(_prefix, ip_range)| Arc::clone(ip_range)))
+    /// Looks up an IP address and returns the matching IPRange (if any),
+    /// dispatching to the v4 or v6 table depending on the address family.
+    pub fn search(&self, ip_addr: IpAddr) -> Option<Arc<IPRange>> {
+        match ip_addr {
+            IpAddr::V4(addr) => self.v4.search(u32::from(addr)),
+            IpAddr::V6(addr) => self.v6.search(u128::from(addr)),
+        }
+    }
+
+    /// Returns every range originated by `asn` (empty if none).
+    pub fn ranges_by_asn(&self, asn: u32) -> Vec<Arc<IPRange>> {
+        self.by_asn.get(&asn).cloned().unwrap_or_default()
+    }
+
+    /// Returns every range tagged with `isp` (case-insensitive; empty if none).
+    pub fn ranges_by_isp(&self, isp: &str) -> Vec<Arc<IPRange>> {
+        self.by_isp.get(&isp.trim().to_lowercase()).cloned().unwrap_or_default()
     }
+
+    /// Total number of v4 + v6 ranges loaded, for `GET /status`.
+    pub fn entry_count(&self) -> usize {
+        self.v4.entries.len() + self.v6.entries.len()
+    }
+}
+
+/// Inserts `ip_range` into a reverse-index bucket, replacing any existing
+/// entry for the same CIDR instead of appending a duplicate — the same
+/// prefix loaded from both the CSV and an MRT/RIB dump under one ASN/ISP
+/// should only show up once (most recently loaded source wins).
+fn upsert_by_cidr(bucket: &mut Vec<Arc<IPRange>>, ip_range: &Arc<IPRange>) {
+    if let Some(existing) = bucket.iter_mut().find(|r| r.cidr_range == ip_range.cidr_range) {
+        *existing = Arc::clone(ip_range);
+    } else {
+        bucket.push(Arc::clone(ip_range));
+    }
+}
+
+/// Parses an ASN string like `"64500"` or `"AS64500"` into a `u32`.
+fn parse_asn(raw: &str) -> Option<u32> {
+    let trimmed = raw.trim();
+    let digits = trimmed
+        .strip_prefix("AS")
+        .or_else(|| trimmed.strip_prefix("as"))
+        .unwrap_or(trimmed);
+    digits.parse::<u32>().ok()
 }
 
-//
-// Example usage and test
-//
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{ Ipv4Addr, Ipv6Addr };
+
+    #[test]
+    fn test_direct_lookup_v4() {
+        let mut lookup = IPRangeDirectLookup::new(20);
+        lookup.insert_range("192.168.1.0/24", "ISP1", "ASN1");
+        lookup.build_table();
 
-    // Dummy parse_cidr implementation for testing.
-    // Replace with your actual implementation.
-    fn dummy_parse_cidr(cidr: &str) -> (u32, u8) {
-        // For testing, assume "192.168.1.0/24" always.
-        (0xc0a80100, 24)
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 42));
+        let result = lookup.search(ip);
+        assert!(result.is_some());
+        assert_eq!(*result.unwrap().cidr_range, "192.168.1.0/24".to_string());
     }
 
-    // Redirect parse_cidr calls in tests to our dummy version.
     #[test]
-    fn test_direct_lookup() {
-        // For testing, override the parse_cidr function.
-        // (In your code, ensure that your real parse_cidr is high-performance.)
-        fn parse_cidr(cidr: &str) -> (u32, u8) {
-            dummy_parse_cidr(cidr)
-        }
-        let _ = parse_cidr;
-
-        // Create the direct lookup with 20 index bits (~1 million entries).
+    fn test_direct_lookup_v6() {
         let mut lookup = IPRangeDirectLookup::new(20);
-        lookup.insert_range("192.168.1.0/24", "ISP1", "ASN1");
-        // You can insert more ranges as needed.
+        lookup.insert_range("2001:db8::/32", "ISP2", "ASN2");
         lookup.build_table();
 
-        // Lookup an IP address in the range, e.g. 192.168.1.42.
-        let ip: u32 = 0xc0a8012a;
+        let ip = IpAddr::V6("2001:db8::1".parse::<Ipv6Addr>().unwrap());
         let result = lookup.search(ip);
         assert!(result.is_some());
-        let ip_range = result.unwrap();
-        assert_eq!(*ip_range.cidr_range, "192.168.1.0/24".to_string());
+        assert_eq!(*result.unwrap().cidr_range, "2001:db8::/32".to_string());
+
+        let miss = IpAddr::V6("2001:db9::1".parse::<Ipv6Addr>().unwrap());
+        assert!(lookup.search(miss).is_none());
+    }
+
+    #[test]
+    fn test_longest_prefix_within_same_slot() {
+        // With index_bits=20, both /24s below fall into the same TBL1 slot
+        // (10.0.0.0/20); a single-level table could only keep one of them.
+        let mut lookup = IPRangeDirectLookup::new(20);
+        lookup.insert_range("10.0.0.0/20", "ISP-agg", "ASN0");
+        lookup.insert_range("10.0.1.0/24", "ISP-a", "ASN1");
+        lookup.insert_range("10.0.2.0/24", "ISP-b", "ASN2");
+        lookup.build_table();
+
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 5));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 2, 5));
+        let fallback = IpAddr::V4(Ipv4Addr::new(10, 0, 3, 5));
+
+        assert_eq!(*lookup.search(a).unwrap().cidr_range, "10.0.1.0/24");
+        assert_eq!(*lookup.search(b).unwrap().cidr_range, "10.0.2.0/24");
+        assert_eq!(*lookup.search(fallback).unwrap().cidr_range, "10.0.0.0/20");
+    }
+
+    #[test]
+    fn test_deep_v6_prefixes_do_not_allocate_exponentially() {
+        // /64 and /128 are both far deeper than the v6 table's 20 index bits
+        // (`new(20)` caps it at min(20, V6_INDEX_BITS)); a flat second-level
+        // chunk sized to that depth would need 2^44 and 2^108 slots
+        // respectively for these.
+        let mut lookup = IPRangeDirectLookup::new(20);
+        lookup.insert_range("2001:db8:1::/64", "ISP-64", "ASN1");
+        lookup.insert_range("2001:db8:1::1/128", "ISP-128", "ASN2");
+        lookup.build_table();
+
+        let host = IpAddr::V6("2001:db8:1::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(*lookup.search(host).unwrap().cidr_range, "2001:db8:1::1/128");
+
+        let other_host_in_64 = IpAddr::V6("2001:db8:1::2".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(*lookup.search(other_host_in_64).unwrap().cidr_range, "2001:db8:1::/64");
+
+        let miss = IpAddr::V6("2001:db8:2::1".parse::<Ipv6Addr>().unwrap());
+        assert!(lookup.search(miss).is_none());
+    }
+
+    #[test]
+    fn test_reverse_index_by_asn_and_isp() {
+        let mut lookup = IPRangeDirectLookup::new(20);
+        lookup.insert_range("192.168.1.0/24", "Acme ISP", "AS64500");
+        lookup.insert_range("192.168.2.0/24", "Acme ISP", "64500");
+        lookup.insert_range("2001:db8::/32", "Other ISP", "64501");
+        lookup.build_table();
+
+        let by_asn = lookup.ranges_by_asn(64500);
+        assert_eq!(by_asn.len(), 2);
+
+        let by_isp = lookup.ranges_by_isp("acme isp");
+        assert_eq!(by_isp.len(), 2);
+
+        assert!(lookup.ranges_by_asn(999).is_empty());
+        assert!(lookup.ranges_by_isp("nobody").is_empty());
+    }
+
+    #[test]
+    fn test_reverse_index_dedupes_same_cidr_from_multiple_sources() {
+        // The same prefix loaded from a CSV and then an MRT/RIB dump under
+        // the same ASN should show up once, not twice, or nftables/ipset
+        // export would emit a duplicate set element.
+        let mut lookup = IPRangeDirectLookup::new(20);
+        lookup.insert_range("198.51.100.0/24", "CSV ISP", "64500");
+        lookup.insert_bgp_route("198.51.100.0/24", "", "64500", RouteAttrs {
+            as_path: vec![64500],
+            med: None,
+            local_pref: None,
+        });
+        lookup.build_table();
+
+        let by_asn = lookup.ranges_by_asn(64500);
+        assert_eq!(by_asn.len(), 1);
     }
 }
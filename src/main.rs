@@ -1,25 +1,39 @@
 use axum::http::StatusCode;
 use axum::response::{ IntoResponse, Response };
 use axum_response_cache::CacheLayer;
+use arc_swap::ArcSwap;
 use configs::Config;
 use rand::Rng;
 use hashmap::IPRangeDirectLookup;
-use routes::{ bulk_handler, handler, AppState };
+use routes::{ asn_handler, bulk_handler, handler, isp_handler, status_handler, AppState, LoadedTable };
 use utils::read_ip_ranges_from_file;
-use std::net::{ Ipv4Addr, Ipv6Addr };
-use std::time::{ Duration, Instant };
+use std::net::{ IpAddr, Ipv4Addr };
+use std::time::{ Duration, Instant, SystemTime };
 use tokio::time;
 use std::sync::Arc;
 use std::fs;
-use tokio::sync::RwLock;
+use std::hash::{ Hash, Hasher };
+use std::collections::hash_map::DefaultHasher;
 use config::Config as ConfigLoader;
 use serde::{ Deserialize, Serialize };
+mod export;
 mod hashmap;
+mod mrt;
+mod two_level;
 mod utils;
 mod routes;
 mod configs;
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Size of the IPv4 `TBL1` index (see `hashmap::IPRangeDirectLookup::new`),
+/// used both at boot and on every reload so rebuilt tables stay comparable.
+const INDEX_BITS: u32 = 20;
+
+/// Baseline interval between file-change checks; jittered by up to
+/// `POLL_JITTER_MS` so a fleet of instances doesn't all poll in lockstep.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const POLL_JITTER_MS: u64 = 5_000;
+
 #[derive(Debug, Clone, Serialize, strum_macros::AsRefStr)]
 pub enum Error {
     NotFound,
@@ -33,63 +47,77 @@ impl IntoResponse for Error {
         }
     }
 }
-async fn monitor_file_changes(state: AppState, file_path: String) {
-    let mut last_mod_time = fs
-        ::metadata(&file_path)
-        .and_then(|meta| meta.modified())
-        .ok();
+/// A cheap fingerprint of a file's content: size, mtime, and a non-crypto
+/// hash of the bytes. `same_content` compares size and hash only, which
+/// catches a real content change while ignoring mtime-only touches (e.g.
+/// `touch`, an atomic rewrite with the same bytes) that would otherwise
+/// trigger a pointless rebuild.
+type FileFingerprint = (u64, SystemTime, u64);
+
+fn same_content(a: &Option<FileFingerprint>, b: &Option<FileFingerprint>) -> bool {
+    match (a, b) {
+        (Some((len_a, _, hash_a)), Some((len_b, _, hash_b))) => len_a == len_b && hash_a == hash_b,
+        (None, None) => true,
+        _ => false,
+    }
+}
 
-    loop {
-        time::sleep(Duration::from_secs(10)).await; // check every 5 minutes
+fn file_fingerprint(file_path: &str) -> Option<FileFingerprint> {
+    let meta = fs::metadata(file_path).ok()?;
+    let mtime = meta.modified().ok()?;
+    let contents = fs::read(file_path).ok()?;
 
-        let metadata = match fs::metadata(&file_path) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("Error getting metadata for {}: {}", file_path, e);
-                continue;
-            }
-        };
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
 
-        let modified_time = match metadata.modified() {
-            Ok(time) => time,
-            Err(e) => {
-                eprintln!("Error getting modified time for {}: {}", file_path, e);
-                continue;
-            }
-        };
+    Some((meta.len(), mtime, hasher.finish()))
+}
 
-        if let Some(last) = last_mod_time {
-            if modified_time > last {
-                println!("File {} changed; updating hashmap...", file_path);
+async fn monitor_file_changes(state: AppState, file_path: String, mrt_file_path: Option<String>) {
+    let mut last_fingerprint = file_fingerprint(&file_path);
+    let mut last_mrt_fingerprint = mrt_file_path.as_deref().and_then(file_fingerprint);
 
-                let mut new_hashmap = IPRangeDirectLookup::new(30);
-                if let Err(e) = read_ip_ranges_from_file(&file_path, &mut new_hashmap) {
-                    eprintln!("Error reloading file {}: {}", file_path, e);
-                    continue;
-                }
+    loop {
+        let jitter_ms = rand::thread_rng().gen_range(0..POLL_JITTER_MS);
+        time::sleep(POLL_INTERVAL + Duration::from_millis(jitter_ms)).await;
+
+        let current_fingerprint = file_fingerprint(&file_path);
+        let current_mrt_fingerprint = mrt_file_path.as_deref().and_then(file_fingerprint);
+
+        if
+            same_content(&current_fingerprint, &last_fingerprint) &&
+            same_content(&current_mrt_fingerprint, &last_mrt_fingerprint)
+        {
+            continue;
+        }
 
-                {
-                    let mut hashmap_guard = state.hashmap.write().await;
-                    *hashmap_guard = new_hashmap;
-                }
-                println!("hashmap successfully updated.");
+        println!("File {} changed; updating hashmap...", file_path);
 
-                last_mod_time = Some(modified_time);
+        let mut new_hashmap = IPRangeDirectLookup::new(INDEX_BITS);
+        if let Err(e) = read_ip_ranges_from_file(&file_path, &mut new_hashmap) {
+            eprintln!("Error reloading file {}: {}", file_path, e);
+            continue;
+        }
+        if let Some(mrt_path) = &mrt_file_path {
+            if let Err(e) = mrt::read_mrt_ribs_from_file(mrt_path, &mut new_hashmap) {
+                eprintln!("Error reloading MRT file {}: {}", mrt_path, e);
+                continue;
             }
-        } else {
-            last_mod_time = Some(modified_time);
         }
+        new_hashmap.build_table();
+
+        // Atomic pointer swap: readers either see the old table or the new
+        // one in full, never a half-rebuilt one, and never block on this.
+        state.hashmap.store(Arc::new(LoadedTable::new(new_hashmap)));
+        println!("hashmap successfully updated.");
+
+        last_fingerprint = current_fingerprint;
+        last_mrt_fingerprint = current_mrt_fingerprint;
     }
 }
 
-fn is_valid_ip(ip_str: &str) -> Option<&'static str> {
-    if ip_str.parse::<Ipv4Addr>().is_ok() {
-        Some("IPv4")
-    } else if ip_str.parse::<Ipv6Addr>().is_ok() {
-        Some("IPv6")
-    } else {
-        None
-    }
+fn is_valid_ip(ip_str: &str) -> Option<IpAddr> {
+    ip_str.parse::<IpAddr>().ok()
 }
 
 #[derive(Deserialize, Debug)]
@@ -103,8 +131,43 @@ struct IpInfo {
     range: Option<String>,
     asn: Option<String>,
     isp: Option<String>,
+    /// Present when the matched range came from a BGP routing-table dump.
+    as_path: Option<Vec<u32>>,
+    origin_asn: Option<u32>,
+    med: Option<u32>,
+    pref: Option<u32>,
     error: Option<String>,
 }
+
+impl IpInfo {
+    fn found(ip: String, range: &hashmap::IPRange) -> Self {
+        IpInfo {
+            ip,
+            range: Some(range.cidr_range.to_string()),
+            asn: Some(range.asn.to_string()),
+            isp: Some(range.isp.to_string()),
+            as_path: range.route.as_ref().map(|r| r.as_path.clone()),
+            origin_asn: range.route.as_ref().and_then(|r| r.origin_asn()),
+            med: range.route.as_ref().and_then(|r| r.med),
+            pref: range.route.as_ref().and_then(|r| r.local_pref),
+            error: None,
+        }
+    }
+
+    fn error(ip: String, message: &str) -> Self {
+        IpInfo {
+            ip,
+            range: None,
+            asn: None,
+            isp: None,
+            as_path: None,
+            origin_asn: None,
+            med: None,
+            pref: None,
+            error: Some(message.to_string()),
+        }
+    }
+}
 fn generate_random_ips(count: usize) -> Vec<Ipv4Addr> {
     let mut rng = rand::thread_rng();
     (0..count)
@@ -128,37 +191,47 @@ async fn main() {
 
     let config: Config = settings.try_deserialize().unwrap();
 
-    let mut hashmap = IPRangeDirectLookup::new(20);
+    let mut hashmap = IPRangeDirectLookup::new(INDEX_BITS);
     let file_path = config.server.file_path;
+    let mrt_file_path = config.server.mrt_file_path;
 
     read_ip_ranges_from_file(&file_path, &mut hashmap).expect("Failed to read ita.cfg");
+    if let Some(mrt_path) = &mrt_file_path {
+        mrt::read_mrt_ribs_from_file(mrt_path, &mut hashmap).expect("Failed to read MRT/RIB dump");
+    }
+    hashmap.build_table();
 
     let binding_address = config.server.binding_address;
 
-    // Wrap the hashmap in an Arc and RwLock.
+    // Load synchronously at boot so the process never serves an empty
+    // table, then hand readers an atomically-swappable pointer to it.
     let state = AppState {
-        hashmap: Arc::new(RwLock::new(hashmap.clone())),
+        hashmap: Arc::new(ArcSwap::from_pointee(LoadedTable::new(hashmap.clone()))),
     };
     let ip_count = 1;
     let ips = generate_random_ips(ip_count);
     println!("random ips generated, testing now ...");
     let start = Instant::now();
     for ip in ips.iter() {
-        hashmap.search(u32::from(*ip));
+        hashmap.search(IpAddr::V4(*ip));
     }
 
     println!("{}", start.elapsed().as_nanos());
     // Spawn the file monitor task.
     let monitor_state = state.clone();
     let monitor_file_path = file_path.clone();
+    let monitor_mrt_file_path = mrt_file_path.clone();
     tokio::spawn(async move {
-        monitor_file_changes(monitor_state, monitor_file_path).await;
+        monitor_file_changes(monitor_state, monitor_file_path, monitor_mrt_file_path).await;
     });
 
     let app = axum::Router
         ::new()
         .route("/single", axum::routing::get(handler))
         .route("/bulk", axum::routing::post(bulk_handler))
+        .route("/asn/:asn", axum::routing::get(asn_handler))
+        .route("/isp/:name", axum::routing::get(isp_handler))
+        .route("/status", axum::routing::get(status_handler))
         .layer(CacheLayer::with_lifespan(20))
         .with_state(state);
 
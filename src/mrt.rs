@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{ self, BufRead };
+
+use crate::hashmap::{ IPRangeDirectLookup, RouteAttrs };
+use crate::utils::parse_cidr;
+
+///
+/// Loads routes from a BGP routing-table dump and feeds them into the
+/// lookup table.
+///
+/// Decoding a full binary MRT TABLE_DUMP_V2 file is out of scope here;
+/// instead this reads the `bgpdump -m` text format that most BGP tooling
+/// already converts MRT RIB dumps into, e.g.:
+///
+/// ```text
+/// TABLE_DUMP2|1700000000|B|192.0.2.1|64500|198.51.100.0/24|64500 64501 64502|IGP|192.0.2.1|0|100|200|NAG||
+/// ```
+///
+/// Fields are `|`-separated: type, timestamp, state, peer IP, peer ASN,
+/// prefix, AS path, origin, next hop, MED, local pref, ... . Malformed or
+/// unrecognized lines (including withdrawals, if a feed is mixed in) are
+/// skipped rather than failing the whole load. Lines with AS_SET segments or
+/// blank MED/local-pref columns are still loaded: AS_SET hops are dropped
+/// from the path and a blank MED/local pref becomes `None`.
+pub fn read_mrt_ribs_from_file(
+    file_path: &str,
+    hashmap: &mut IPRangeDirectLookup
+) -> io::Result<()> {
+    let file = File::open(file_path)?;
+    let reader = io::BufReader::new(file);
+
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(route) = parse_table_dump2_line(&line) {
+            let isp = "";
+            let asn = route.attrs
+                .origin_asn()
+                .map(|asn| asn.to_string())
+                .unwrap_or_default();
+            hashmap.insert_bgp_route(&route.prefix, isp, &asn, route.attrs);
+        }
+    }
+
+    Ok(())
+}
+
+struct MrtRoute {
+    prefix: String,
+    attrs: RouteAttrs,
+}
+
+/// Parses a single `TABLE_DUMP2|...|B|...` RIB line, returning `None` if the
+/// line isn't a RIB entry we understand (wrong type, withdrawal, too few
+/// fields, unparsable prefix).
+fn parse_table_dump2_line(line: &str) -> Option<MrtRoute> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 11 || fields[0] != "TABLE_DUMP2" || fields[2] != "B" {
+        return None;
+    }
+
+    let prefix = fields[5].trim();
+    parse_cidr(prefix)?;
+
+    let as_path = parse_as_path(fields[6]);
+    let med = fields[9].trim().parse::<u32>().ok();
+    let local_pref = fields[10].trim().parse::<u32>().ok();
+
+    Some(MrtRoute {
+        prefix: prefix.to_string(),
+        attrs: RouteAttrs { as_path, med, local_pref },
+    })
+}
+
+/// Parses an AS-path column into an ordered list of ASNs, dropping AS_SET
+/// segments (`{...}`) since they don't represent a single deterministic hop.
+fn parse_as_path(field: &str) -> Vec<u32> {
+    field
+        .split_whitespace()
+        .filter_map(|hop| hop.parse::<u32>().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table_dump2_line() {
+        let line =
+            "TABLE_DUMP2|1700000000|B|192.0.2.1|64500|198.51.100.0/24|64500 64501 64502|IGP|192.0.2.1|0|100|200|NAG||";
+        let route = parse_table_dump2_line(line).expect("should parse");
+        assert_eq!(route.prefix, "198.51.100.0/24");
+        assert_eq!(route.attrs.as_path, vec![64500, 64501, 64502]);
+        assert_eq!(route.attrs.origin_asn(), Some(64502));
+        assert_eq!(route.attrs.med, Some(0));
+        assert_eq!(route.attrs.local_pref, Some(100));
+    }
+
+    #[test]
+    fn test_parse_table_dump2_line_skips_non_rib_entries() {
+        assert!(parse_table_dump2_line("TABLE_DUMP2|1700000000|W|192.0.2.1|64500|198.51.100.0/24||").is_none());
+        assert!(parse_table_dump2_line("garbage").is_none());
+    }
+
+    #[test]
+    fn test_parse_table_dump2_line_skips_unparsable_prefix() {
+        let line = "TABLE_DUMP2|1700000000|B|192.0.2.1|64500|not-a-prefix|64500|IGP|192.0.2.1|0|100|200|NAG||";
+        assert!(parse_table_dump2_line(line).is_none());
+    }
+}
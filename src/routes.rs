@@ -1,15 +1,29 @@
-use axum::{ extract::State, response::Json as JSONResponse };
+use axum::{ extract::{ Path, State }, response::{ Json as JSONResponse, Response } };
 
-use serde::Deserialize;
+use arc_swap::ArcSwap;
+use serde::{ Deserialize, Serialize };
 use axum_macros::debug_handler;
-use std::{ net::Ipv4Addr, sync::Arc };
-use tokio::sync::RwLock;
+use std::sync::Arc;
+use std::time::{ SystemTime, UNIX_EPOCH };
 use futures::StreamExt;
-use crate::{ hashmap::IPRangeHashMap, is_valid_ip, BulkIpParam, Error, IpInfo, Result };
+use crate::{ export::{ self, ExportFormat }, hashmap::IPRangeDirectLookup, is_valid_ip, BulkIpParam, Error, IpInfo, Result };
+
+/// A built lookup table plus the metadata `GET /status` reports on it.
+pub struct LoadedTable {
+    pub table: IPRangeDirectLookup,
+    pub built_at: SystemTime,
+}
+
+impl LoadedTable {
+    pub fn new(table: IPRangeDirectLookup) -> Self {
+        LoadedTable { table, built_at: SystemTime::now() }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
-    pub hashmap: Arc<RwLock<IPRangeHashMap>>,
+    /// Swapped atomically on reload so readers never block on a rebuild.
+    pub hashmap: Arc<ArcSwap<LoadedTable>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -23,25 +37,15 @@ pub async fn handler(
     State(state): axum::extract::State<AppState>
 ) -> Result<axum::response::Json<IpInfo>> {
     match is_valid_ip(&params.ip) {
-        Some("IPv4") => {
-            let ip = params.ip.parse::<std::net::Ipv4Addr>().unwrap();
-            // Acquire a read lock on the hashmap.
-            let hashmap = state.hashmap.read().await;
-            if let Some(res) = hashmap.search(u32::from(ip)) {
-                Ok(
-                    axum::response::Json(IpInfo {
-                        ip: params.ip.to_string(),
-                        range: Some(res.cidr_range.to_string()),
-                        asn: Some(res.asn.to_string()),
-                        isp: Some(res.isp.to_string()),
-                        error: None,
-                    })
-                )
+        Some(ip) => {
+            let loaded = state.hashmap.load();
+            if let Some(res) = loaded.table.search(ip) {
+                Ok(axum::response::Json(IpInfo::found(params.ip.to_string(), &res)))
             } else {
                 Err(Error::NotFound)
             }
         }
-        _ => Err(Error::InvalidDate),
+        None => Err(Error::InvalidDate),
     }
 }
 
@@ -56,55 +60,15 @@ pub async fn bulk_handler(
             let state = state.clone();
             async move {
                 match is_valid_ip(&ip_str) {
-                    Some("IPv4") => {
-                        let ip = match ip_str.parse::<Ipv4Addr>() {
-                            Ok(ip) => ip,
-                            Err(_) => {
-                                return IpInfo {
-                                    ip: ip_str.clone(),
-                                    range: None,
-                                    asn: None,
-                                    isp: None,
-                                    error: Some("Invalid IPv4 format".to_string()),
-                                };
-                            }
-                        };
-
-                        let hashmap = state.hashmap.read().await;
-                        if let Some(info) = hashmap.search(u32::from(ip)) {
-                            IpInfo {
-                                ip: ip_str.clone(),
-                                range: Some(info.cidr_range.to_string()),
-                                asn: Some(info.asn.to_string()),
-                                isp: Some(info.isp.to_string()),
-                                error: None,
-                            }
+                    Some(ip) => {
+                        let loaded = state.hashmap.load();
+                        if let Some(info) = loaded.table.search(ip) {
+                            IpInfo::found(ip_str.clone(), &info)
                         } else {
-                            IpInfo {
-                                ip: ip_str.clone(),
-                                range: None,
-                                asn: None,
-                                isp: None,
-                                error: Some("IP not found".to_string()),
-                            }
+                            IpInfo::error(ip_str.clone(), "IP not found")
                         }
                     }
-                    Some("IPv6") =>
-                        IpInfo {
-                            ip: ip_str.clone(),
-                            range: None,
-                            asn: None,
-                            isp: None,
-                            error: Some("IPv6 lookup not supported".to_string()),
-                        },
-                    _ =>
-                        IpInfo {
-                            ip: ip_str.clone(),
-                            range: None,
-                            asn: None,
-                            isp: None,
-                            error: Some("Invalid IP address".to_string()),
-                        },
+                    None => IpInfo::error(ip_str.clone(), "Invalid IP address"),
                 }
             }
         })
@@ -112,3 +76,47 @@ pub async fn bulk_handler(
 
     JSONResponse(results)
 }
+
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+#[debug_handler]
+pub async fn asn_handler(
+    Path(asn): Path<u32>,
+    axum::extract::Query(query): axum::extract::Query<ExportQuery>,
+    State(state): State<AppState>
+) -> Response {
+    let loaded = state.hashmap.load();
+    let ranges = loaded.table.ranges_by_asn(asn);
+    export::render(&ranges, ExportFormat::from_query(query.format.as_deref()), &format!("asn{asn}"))
+}
+
+#[debug_handler]
+pub async fn isp_handler(
+    Path(isp): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ExportQuery>,
+    State(state): State<AppState>
+) -> Response {
+    let loaded = state.hashmap.load();
+    let ranges = loaded.table.ranges_by_isp(&isp);
+    export::render(&ranges, ExportFormat::from_query(query.format.as_deref()), &isp)
+}
+
+/// Reports when the currently served table was built and how big it is.
+#[derive(Serialize)]
+pub struct StatusResponse {
+    built_at: u64,
+    entry_count: usize,
+}
+
+#[debug_handler]
+pub async fn status_handler(State(state): State<AppState>) -> JSONResponse<StatusResponse> {
+    let loaded = state.hashmap.load();
+    let built_at = loaded.built_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    JSONResponse(StatusResponse {
+        built_at,
+        entry_count: loaded.table.entry_count(),
+    })
+}
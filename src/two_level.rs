@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use crate::hashmap::IPRange;
+
+/// Returns a mask with the low `n` bits set (saturating at all 128 bits).
+fn low_bits_mask(n: u32) -> u128 {
+    if n >= 128 { u128::MAX } else { (1u128 << n) - 1 }
+}
+
+/// One entry in a `TBL1` slot's second-level chunk: a prefix more specific
+/// than `index_bits`, recorded as how many bits below the index it covers
+/// and the value those bits must match.
+#[derive(Clone)]
+struct ChunkEntry {
+    covered_bits: u32,
+    value: u128,
+    prefix: u8,
+    range: Arc<IPRange>,
+}
+
+/// A first-level table slot: either empty, a direct match good for every
+/// address that indexes into it, or a second-level chunk for addresses that
+/// need more than `index_bits` of precision.
+#[derive(Clone)]
+enum Tbl1Slot {
+    Empty,
+    Direct(u8, Arc<IPRange>),
+    /// A handful of entries rather than a table of size `2^covered_bits`:
+    /// IPv6 prefixes can be over 100 bits deeper than `index_bits`, so a
+    /// flat table sized to that depth is not an option.
+    Chunk(Vec<ChunkEntry>),
+}
+
+/// A two-level direct lookup table (DIR-n-m, generalized to an arbitrary
+/// address width and index size).
+///
+/// `TBL1` has `2^index_bits` entries, indexed by the top `index_bits` of the
+/// address, and stores the match directly whenever a prefix no longer than
+/// `index_bits` is the best one for that slot. Slots that also have a more
+/// specific prefix underneath instead point at a small list of second-level
+/// entries, searched linearly for the longest match.
+///
+/// Insertion always keeps the longest matching prefix per address
+/// (leaf-pushing). Among prefixes of equal length for the same address, the
+/// most recently inserted one wins, so loading a more specific data source
+/// (e.g. a RIB dump) after a coarser one (e.g. a static CSV) lets it
+/// override matching entries.
+#[derive(Clone)]
+pub struct TwoLevelTable {
+    table: Vec<Tbl1Slot>,
+    total_bits: u32,
+    index_bits: u32,
+}
+
+impl TwoLevelTable {
+    /// * `total_bits` is the address width (32 for IPv4, 128 for IPv6).
+    /// * `index_bits` sizes `TBL1` (`2^index_bits` entries).
+    pub fn new(total_bits: u32, index_bits: u32) -> Self {
+        assert!(index_bits <= total_bits);
+        TwoLevelTable {
+            table: vec![Tbl1Slot::Empty; 1usize << index_bits],
+            total_bits,
+            index_bits,
+        }
+    }
+
+    /// Resets every `TBL1` slot back to empty, ready for a fresh build.
+    pub fn clear(&mut self) {
+        self.table.fill(Tbl1Slot::Empty);
+    }
+
+    /// Inserts a prefix, leaf-pushing it into every address it covers.
+    ///
+    /// `network` is an address within the prefix (host bits may be set;
+    /// they're masked off here) represented as the low `total_bits` bits of
+    /// a `u128`.
+    pub fn insert(&mut self, network: u128, prefix: u8, ip_range: &Arc<IPRange>) {
+        let shift = self.total_bits - self.index_bits;
+        let free_bits = self.total_bits - (prefix as u32);
+        let base = network & !low_bits_mask(free_bits);
+
+        if (prefix as u32) <= self.index_bits {
+            let first_index = (base >> shift) as usize;
+            let last_index = ((base | low_bits_mask(free_bits)) >> shift) as usize;
+            for index in first_index..=last_index {
+                self.insert_general(index, prefix, ip_range);
+            }
+        } else {
+            let index = (base >> shift) as usize;
+            let covered_bits = (prefix as u32) - self.index_bits;
+            let window_free_bits = shift - covered_bits;
+            let value = (base >> window_free_bits) & low_bits_mask(covered_bits);
+            self.insert_specific(index, covered_bits, value, prefix, ip_range);
+        }
+    }
+
+    /// Inserts a prefix no longer than `index_bits` into a single `TBL1` slot.
+    fn insert_general(&mut self, index: usize, prefix: u8, ip_range: &Arc<IPRange>) {
+        match &mut self.table[index] {
+            slot @ Tbl1Slot::Empty => {
+                *slot = Tbl1Slot::Direct(prefix, Arc::clone(ip_range));
+            }
+            Tbl1Slot::Direct(existing_prefix, existing_range) => {
+                if prefix >= *existing_prefix {
+                    *existing_prefix = prefix;
+                    *existing_range = Arc::clone(ip_range);
+                }
+            }
+            Tbl1Slot::Chunk(entries) => {
+                // A chunk already exists because a more specific prefix lives
+                // under this slot; this one only acts as a fallback, so it
+                // covers the whole window (0 covered bits).
+                upsert_entry(entries, 0, 0, prefix, ip_range);
+            }
+        }
+    }
+
+    /// Inserts a prefix longer than `index_bits` at `table[index]`.
+    fn insert_specific(
+        &mut self,
+        index: usize,
+        covered_bits: u32,
+        value: u128,
+        prefix: u8,
+        ip_range: &Arc<IPRange>
+    ) {
+        match &mut self.table[index] {
+            Tbl1Slot::Empty => {
+                self.table[index] = Tbl1Slot::Chunk(
+                    vec![ChunkEntry { covered_bits, value, prefix, range: Arc::clone(ip_range) }]
+                );
+            }
+            Tbl1Slot::Direct(fallback_prefix, fallback_range) => {
+                // Everything that doesn't match the new entry still falls
+                // back to the less specific route that used to cover the
+                // whole slot.
+                let fallback = ChunkEntry {
+                    covered_bits: 0,
+                    value: 0,
+                    prefix: *fallback_prefix,
+                    range: Arc::clone(fallback_range),
+                };
+                let entry = ChunkEntry { covered_bits, value, prefix, range: Arc::clone(ip_range) };
+                self.table[index] = Tbl1Slot::Chunk(vec![fallback, entry]);
+            }
+            Tbl1Slot::Chunk(entries) => {
+                upsert_entry(entries, covered_bits, value, prefix, ip_range);
+            }
+        }
+    }
+
+    /// Looks up `addr` (the low `total_bits` bits of a `u128`) and returns
+    /// the longest matching prefix's range, if any.
+    pub fn search(&self, addr: u128) -> Option<Arc<IPRange>> {
+        let shift = self.total_bits - self.index_bits;
+        let index = (addr >> shift) as usize;
+        match self.table.get(index)? {
+            Tbl1Slot::Empty => None,
+            Tbl1Slot::Direct(_, ip_range) => Some(Arc::clone(ip_range)),
+            Tbl1Slot::Chunk(entries) => {
+                let window = addr & low_bits_mask(shift);
+                entries
+                    .iter()
+                    .filter(|entry| {
+                        let window_free_bits = shift - entry.covered_bits;
+                        (window >> window_free_bits) == entry.value
+                    })
+                    .max_by_key(|entry| entry.covered_bits)
+                    .map(|entry| Arc::clone(&entry.range))
+            }
+        }
+    }
+}
+
+/// Inserts `(prefix, ip_range)` into `entries` keyed by `(covered_bits,
+/// value)`, replacing whatever is already there for an equally specific
+/// match (most recent insert wins on a tie) and leaving more specific
+/// entries untouched.
+fn upsert_entry(entries: &mut Vec<ChunkEntry>, covered_bits: u32, value: u128, prefix: u8, ip_range: &Arc<IPRange>) {
+    if let Some(existing) = entries.iter_mut().find(|e| e.covered_bits == covered_bits && e.value == value) {
+        if prefix >= existing.prefix {
+            existing.prefix = prefix;
+            existing.range = Arc::clone(ip_range);
+        }
+    } else {
+        entries.push(ChunkEntry { covered_bits, value, prefix, range: Arc::clone(ip_range) });
+    }
+}
@@ -1,17 +1,43 @@
-use std::net::Ipv4Addr;
+use std::net::{ Ipv4Addr, Ipv6Addr };
 use std::fs::File;
 
 use std::io::{ self, BufRead };
-use crate::hashmap::IPRangeHashMap;
+use crate::hashmap::IPRangeDirectLookup;
 
-pub fn parse_cidr(cidr: &str) -> (u32, u8) {
-    let parts: Vec<&str> = cidr.split('/').collect();
-    let ip: u32 = parts[0].parse::<Ipv4Addr>().unwrap().into();
-    let prefix_len: u8 = parts[1].parse().unwrap();
-    (ip, prefix_len)
+/// A CIDR network parsed into the widest integer that fits its family,
+/// tagged with the family it came from.
+#[derive(Debug)]
+pub enum CidrNetwork {
+    V4(u32, u8),
+    V6(u128, u8),
 }
 
-pub fn read_ip_ranges_from_file(file_path: &str, hashmap: &mut IPRangeHashMap) -> io::Result<()> {
+/// Parses a `<address>/<prefix>` string, returning `None` for anything
+/// malformed (missing slash, unparsable prefix, unparsable address, or a
+/// prefix longer than the address family allows) instead of panicking —
+/// callers loading CSV/RIB files at scale always hit a few bad lines and
+/// should skip them rather than crash.
+pub fn parse_cidr(cidr: &str) -> Option<CidrNetwork> {
+    let (address, prefix_len) = cidr.split_once('/')?;
+    let prefix_len: u8 = prefix_len.parse().ok()?;
+
+    if let Ok(ip) = address.parse::<Ipv4Addr>() {
+        if prefix_len > 32 {
+            return None;
+        }
+        return Some(CidrNetwork::V4(u32::from(ip), prefix_len));
+    }
+    let ip: Ipv6Addr = address.parse().ok()?;
+    if prefix_len > 128 {
+        return None;
+    }
+    Some(CidrNetwork::V6(u128::from(ip), prefix_len))
+}
+
+pub fn read_ip_ranges_from_file(
+    file_path: &str,
+    hashmap: &mut IPRangeDirectLookup
+) -> io::Result<()> {
     let file = File::open(file_path)?;
     let reader = io::BufReader::new(file);
 
@@ -28,3 +54,42 @@ pub fn read_ip_ranges_from_file(file_path: &str, hashmap: &mut IPRangeHashMap) -
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cidr_v4_and_v6() {
+        match parse_cidr("192.168.1.0/24") {
+            Some(CidrNetwork::V4(network, prefix)) => {
+                assert_eq!(network, u32::from(Ipv4Addr::new(192, 168, 1, 0)));
+                assert_eq!(prefix, 24);
+            }
+            other => panic!("expected V4, got {other:?}"),
+        }
+
+        match parse_cidr("2001:db8::/32") {
+            Some(CidrNetwork::V6(network, prefix)) => {
+                assert_eq!(network, u128::from("2001:db8::".parse::<Ipv6Addr>().unwrap()));
+                assert_eq!(prefix, 32);
+            }
+            other => panic!("expected V6, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_cidr_rejects_malformed_input() {
+        assert!(parse_cidr("not-a-cidr").is_none());
+        assert!(parse_cidr("192.168.1.0").is_none());
+        assert!(parse_cidr("192.168.1.0/not-a-number").is_none());
+        assert!(parse_cidr("not-an-ip/24").is_none());
+    }
+
+    #[test]
+    fn test_parse_cidr_rejects_out_of_range_prefix() {
+        assert!(parse_cidr("1.2.3.4/99").is_none());
+        assert!(parse_cidr("1.2.3.4/33").is_none());
+        assert!(parse_cidr("2001:db8::/129").is_none());
+    }
+}